@@ -3,6 +3,7 @@ use Result;
 use errno::Errno;
 use sys::time::TimeVal;
 use libc::{self, c_int, uint8_t, c_void, socklen_t};
+use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::unix::io::RawFd;
 
@@ -145,8 +146,22 @@ sockopt_impl!(SetOnly, Ipv6AddMembership, libc::IPPROTO_IPV6, libc::IPV6_JOIN_GR
 sockopt_impl!(SetOnly, Ipv6DropMembership, libc::IPPROTO_IPV6, libc::IPV6_LEAVE_GROUP, super::ipv6_mreq);
 sockopt_impl!(Both, IpMulticastTtl, libc::IPPROTO_IP, libc::IP_MULTICAST_TTL, u8);
 sockopt_impl!(Both, IpMulticastLoop, libc::IPPROTO_IP, libc::IP_MULTICAST_LOOP, bool);
+#[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "ios", target_os = "linux", target_os = "macos", target_os = "netbsd", target_os = "openbsd"))]
+sockopt_impl!(Both, IpMulticastIf, libc::IPPROTO_IP, libc::IP_MULTICAST_IF, libc::in_addr, GetIpv4Addr, SetIpv4Addr);
+#[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "ios", target_os = "linux", target_os = "macos", target_os = "netbsd", target_os = "openbsd"))]
+sockopt_impl!(Both, Ipv6MulticastIf, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_IF, u32);
+#[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "ios", target_os = "linux", target_os = "macos", target_os = "netbsd", target_os = "openbsd"))]
+sockopt_impl!(Both, IpTtl, libc::IPPROTO_IP, libc::IP_TTL, i32);
+#[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "ios", target_os = "linux", target_os = "macos", target_os = "netbsd", target_os = "openbsd"))]
+sockopt_impl!(Both, Ipv6UnicastHops, libc::IPPROTO_IPV6, libc::IPV6_UNICAST_HOPS, i32);
+#[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "ios", target_os = "linux", target_os = "macos", target_os = "netbsd", target_os = "openbsd"))]
+sockopt_impl!(Both, Ipv6MulticastHops, libc::IPPROTO_IPV6, libc::IPV6_MULTICAST_HOPS, i32);
+#[cfg(any(target_os = "android", target_os = "dragonfly", target_os = "freebsd", target_os = "ios", target_os = "linux", target_os = "macos", target_os = "netbsd", target_os = "openbsd"))]
+sockopt_impl!(Both, Ipv6V6Only, libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, bool);
 sockopt_impl!(Both, ReceiveTimeout, libc::SOL_SOCKET, libc::SO_RCVTIMEO, TimeVal);
 sockopt_impl!(Both, SendTimeout, libc::SOL_SOCKET, libc::SO_SNDTIMEO, TimeVal);
+sockopt_impl!(Both, ReceiveTimeoutOpt, libc::SOL_SOCKET, libc::SO_RCVTIMEO, Option<TimeVal>, GetOptTimeVal, SetOptTimeVal);
+sockopt_impl!(Both, SendTimeoutOpt, libc::SOL_SOCKET, libc::SO_SNDTIMEO, Option<TimeVal>, GetOptTimeVal, SetOptTimeVal);
 sockopt_impl!(Both, Broadcast, libc::SOL_SOCKET, libc::SO_BROADCAST, bool);
 sockopt_impl!(Both, OobInline, libc::SOL_SOCKET, libc::SO_OOBINLINE, bool);
 sockopt_impl!(GetOnly, SocketError, libc::SOL_SOCKET, libc::SO_ERROR, i32);
@@ -162,6 +177,18 @@ sockopt_impl!(Both, TcpKeepAlive, libc::IPPROTO_TCP, libc::TCP_KEEPALIVE, u32);
           target_os = "android",
           target_os = "nacl"))]
 sockopt_impl!(Both, TcpKeepIdle, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, u32);
+#[cfg(any(target_os = "freebsd",
+          target_os = "dragonfly",
+          target_os = "linux",
+          target_os = "android",
+          target_os = "nacl"))]
+sockopt_impl!(Both, TcpKeepCnt, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, u32);
+#[cfg(any(target_os = "freebsd",
+          target_os = "dragonfly",
+          target_os = "linux",
+          target_os = "android",
+          target_os = "nacl"))]
+sockopt_impl!(Both, TcpKeepInterval, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, u32);
 sockopt_impl!(Both, RcvBuf, libc::SOL_SOCKET, libc::SO_RCVBUF, usize);
 sockopt_impl!(Both, SndBuf, libc::SOL_SOCKET, libc::SO_SNDBUF, usize);
 #[cfg(any(target_os = "linux", target_os = "android"))]
@@ -173,6 +200,72 @@ sockopt_impl!(GetOnly, AcceptConn, libc::SOL_SOCKET, libc::SO_ACCEPTCONN, bool);
 #[cfg(any(target_os = "linux", target_os = "android"))]
 sockopt_impl!(GetOnly, OriginalDst, libc::SOL_IP, libc::SO_ORIGINAL_DST, libc::sockaddr_in);
 sockopt_impl!(Both, ReceiveTimestamp, libc::SOL_SOCKET, libc::SO_TIMESTAMP, bool);
+#[cfg(any(target_os = "linux", target_os = "android"))]
+sockopt_impl!(Both, BindToDevice, libc::SOL_SOCKET, libc::SO_BINDTODEVICE, CString, GetCString, SetCStr);
+#[cfg(any(target_os = "linux", target_os = "android"))]
+sockopt_impl!(GetOnly, SocketProtocol, libc::SOL_SOCKET, libc::SO_PROTOCOL, i32);
+
+/// `SO_DOMAIN` returns an `AddressFamily`, but the kernel can report
+/// families this crate doesn't model (e.g. `AF_VSOCK`, `AF_PACKET`); go
+/// through a hand-written `GetSockOpt` impl instead of `sockopt_impl!` so
+/// an unrecognized value can return `EINVAL` rather than panicking.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Copy, Clone, Debug)]
+pub struct SocketDomain;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl GetSockOpt for SocketDomain {
+    type Val = super::AddressFamily;
+
+    fn get(&self, fd: RawFd) -> Result<super::AddressFamily> {
+        unsafe {
+            let mut getter: GetStruct<c_int> = Get::blank();
+
+            let res = libc::getsockopt(fd, libc::SOL_SOCKET, libc::SO_DOMAIN,
+                                       getter.ffi_ptr(),
+                                       getter.ffi_len());
+            try!(Errno::result(res));
+
+            let domain = getter.unwrap();
+            super::AddressFamily::from_i32(domain).ok_or_else(|| Errno::EINVAL.into())
+        }
+    }
+}
+
+/// Configures a socket's idle time, probe interval, and probe count for
+/// TCP keepalive in a single call, leaving any field set to `None`
+/// untouched.
+#[cfg(any(target_os = "freebsd",
+          target_os = "dragonfly",
+          target_os = "linux",
+          target_os = "android",
+          target_os = "nacl"))]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TcpKeepalive {
+    pub idle: Option<u32>,
+    pub interval: Option<u32>,
+    pub count: Option<u32>,
+}
+
+#[cfg(any(target_os = "freebsd",
+          target_os = "dragonfly",
+          target_os = "linux",
+          target_os = "android",
+          target_os = "nacl"))]
+impl TcpKeepalive {
+    pub fn set(&self, fd: RawFd) -> Result<()> {
+        if let Some(idle) = self.idle {
+            try!(super::setsockopt(fd, TcpKeepIdle, &idle));
+        }
+        if let Some(interval) = self.interval {
+            try!(super::setsockopt(fd, TcpKeepInterval, &interval));
+        }
+        if let Some(count) = self.count {
+            try!(super::setsockopt(fd, TcpKeepCnt, &count));
+        }
+        Ok(())
+    }
+}
 
 /*
  *
@@ -373,6 +466,148 @@ unsafe impl<'a> Set<'a, usize> for SetUsize {
     }
 }
 
+struct GetIpv4Addr {
+    len: socklen_t,
+    val: libc::in_addr,
+}
+
+unsafe impl Get<libc::in_addr> for GetIpv4Addr {
+    unsafe fn blank() -> Self {
+        GetIpv4Addr {
+            len: mem::size_of::<libc::in_addr>() as socklen_t,
+            val: mem::zeroed(),
+        }
+    }
+
+    fn ffi_ptr(&mut self) -> *mut c_void {
+        &mut self.val as *mut libc::in_addr as *mut c_void
+    }
+
+    fn ffi_len(&mut self) -> *mut socklen_t {
+        &mut self.len
+    }
+
+    unsafe fn unwrap(self) -> libc::in_addr {
+        assert!(self.len as usize == mem::size_of::<libc::in_addr>(), "invalid getsockopt implementation");
+        self.val
+    }
+}
+
+struct SetIpv4Addr {
+    val: libc::in_addr,
+}
+
+unsafe impl<'a> Set<'a, libc::in_addr> for SetIpv4Addr {
+    fn new(val: &'a libc::in_addr) -> SetIpv4Addr {
+        SetIpv4Addr { val: *val }
+    }
+
+    fn ffi_ptr(&self) -> *const c_void {
+        &self.val as *const libc::in_addr as *const c_void
+    }
+
+    fn ffi_len(&self) -> socklen_t {
+        mem::size_of::<libc::in_addr>() as socklen_t
+    }
+}
+
+struct GetOptTimeVal {
+    len: socklen_t,
+    val: TimeVal,
+}
+
+unsafe impl Get<Option<TimeVal>> for GetOptTimeVal {
+    unsafe fn blank() -> Self {
+        GetOptTimeVal {
+            len: mem::size_of::<TimeVal>() as socklen_t,
+            val: mem::zeroed(),
+        }
+    }
+
+    fn ffi_ptr(&mut self) -> *mut c_void {
+        &mut self.val as *mut TimeVal as *mut c_void
+    }
+
+    fn ffi_len(&mut self) -> *mut socklen_t {
+        &mut self.len
+    }
+
+    unsafe fn unwrap(self) -> Option<TimeVal> {
+        assert!(self.len as usize == mem::size_of::<TimeVal>(), "invalid getsockopt implementation");
+        if self.val.tv_sec() == 0 && self.val.tv_usec() == 0 {
+            None
+        } else {
+            Some(self.val)
+        }
+    }
+}
+
+struct SetOptTimeVal {
+    val: TimeVal,
+}
+
+unsafe impl<'a> Set<'a, Option<TimeVal>> for SetOptTimeVal {
+    fn new(val: &'a Option<TimeVal>) -> SetOptTimeVal {
+        SetOptTimeVal {
+            val: val.unwrap_or_else(|| unsafe { mem::zeroed() }),
+        }
+    }
+
+    fn ffi_ptr(&self) -> *const c_void {
+        &self.val as *const TimeVal as *const c_void
+    }
+
+    fn ffi_len(&self) -> socklen_t {
+        mem::size_of::<TimeVal>() as socklen_t
+    }
+}
+
+struct GetCString {
+    len: socklen_t,
+    buf: [u8; libc::IFNAMSIZ],
+}
+
+unsafe impl Get<CString> for GetCString {
+    unsafe fn blank() -> Self {
+        GetCString {
+            len: mem::size_of::<[u8; libc::IFNAMSIZ]>() as socklen_t,
+            buf: mem::zeroed(),
+        }
+    }
+
+    fn ffi_ptr(&mut self) -> *mut c_void {
+        self.buf.as_mut_ptr() as *mut c_void
+    }
+
+    fn ffi_len(&mut self) -> *mut socklen_t {
+        &mut self.len
+    }
+
+    unsafe fn unwrap(self) -> CString {
+        let buf = &self.buf[..self.len as usize];
+        let nul = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        CString::new(&buf[..nul]).unwrap()
+    }
+}
+
+struct SetCStr<'a> {
+    ptr: &'a CStr,
+}
+
+unsafe impl<'a> Set<'a, CString> for SetCStr<'a> {
+    fn new(val: &'a CString) -> SetCStr<'a> {
+        SetCStr { ptr: val.as_c_str() }
+    }
+
+    fn ffi_ptr(&self) -> *const c_void {
+        self.ptr.as_ptr() as *const c_void
+    }
+
+    fn ffi_len(&self) -> socklen_t {
+        self.ptr.to_bytes().len() as socklen_t
+    }
+}
+
 #[cfg(test)]
 mod test {
     #[cfg(all(target_os = "linux", not(target_arch = "arm")))]
@@ -426,4 +661,106 @@ mod test {
         assert!(s_listening2);
         close(s).unwrap();
     }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn can_bind_to_device() {
+        use super::super::*;
+        use ::unistd::close;
+        use std::ffi::CString;
+
+        let s = socket(AddressFamily::Inet, SockType::Datagram, SockFlag::empty(), None).unwrap();
+        let dev = CString::new("lo").unwrap();
+        setsockopt(s, super::BindToDevice, &dev).unwrap();
+        let dev2 = getsockopt(s, super::BindToDevice).unwrap();
+        assert_eq!(dev, dev2);
+        close(s).unwrap();
+    }
+
+    #[test]
+    fn can_set_ipv6_v6only() {
+        use super::super::*;
+        use ::unistd::close;
+
+        let s = socket(AddressFamily::Inet6, SockType::Stream, SockFlag::empty(), None).unwrap();
+        setsockopt(s, super::Ipv6V6Only, &true).unwrap();
+        assert!(getsockopt(s, super::Ipv6V6Only).unwrap());
+        close(s).unwrap();
+    }
+
+    #[test]
+    fn can_round_trip_ip_multicast_if() {
+        use super::super::*;
+        use ::unistd::close;
+        use libc;
+
+        let s = socket(AddressFamily::Inet, SockType::Datagram, SockFlag::empty(), None).unwrap();
+        let addr = libc::in_addr { s_addr: 0 };
+        setsockopt(s, super::IpMulticastIf, &addr).unwrap();
+        let addr2 = getsockopt(s, super::IpMulticastIf).unwrap();
+        assert_eq!(addr.s_addr, addr2.s_addr);
+        close(s).unwrap();
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn can_get_socket_domain_and_protocol() {
+        use super::super::*;
+        use ::unistd::close;
+        use libc;
+
+        let s = socket(AddressFamily::Inet, SockType::Stream, SockFlag::empty(), None).unwrap();
+        assert_eq!(getsockopt(s, super::SocketDomain).unwrap(), AddressFamily::Inet);
+        assert_eq!(getsockopt(s, super::SocketProtocol).unwrap(), libc::IPPROTO_TCP);
+        close(s).unwrap();
+    }
+
+    #[test]
+    fn receive_timeout_opt_round_trips() {
+        use super::super::*;
+        use ::unistd::close;
+        use sys::time::{TimeVal, TimeValLike};
+
+        let s = socket(AddressFamily::Inet, SockType::Datagram, SockFlag::empty(), None).unwrap();
+        assert_eq!(getsockopt(s, super::ReceiveTimeoutOpt).unwrap(), None);
+
+        let tv = TimeVal::seconds(1);
+        setsockopt(s, super::ReceiveTimeoutOpt, &Some(tv)).unwrap();
+        assert_eq!(getsockopt(s, super::ReceiveTimeoutOpt).unwrap(), Some(tv));
+
+        setsockopt(s, super::ReceiveTimeoutOpt, &None).unwrap();
+        assert_eq!(getsockopt(s, super::ReceiveTimeoutOpt).unwrap(), None);
+        close(s).unwrap();
+    }
+
+    #[cfg(any(target_os = "freebsd",
+              target_os = "dragonfly",
+              target_os = "linux",
+              target_os = "android",
+              target_os = "nacl"))]
+    #[test]
+    fn can_set_tcp_keepalive() {
+        use super::super::*;
+        use ::unistd::close;
+
+        let s = socket(AddressFamily::Inet, SockType::Stream, SockFlag::empty(), None).unwrap();
+
+        setsockopt(s, super::TcpKeepCnt, &4).unwrap();
+        assert_eq!(getsockopt(s, super::TcpKeepCnt).unwrap(), 4);
+
+        setsockopt(s, super::TcpKeepInterval, &30).unwrap();
+        assert_eq!(getsockopt(s, super::TcpKeepInterval).unwrap(), 30);
+
+        let keepalive = super::TcpKeepalive {
+            idle: Some(60),
+            interval: Some(10),
+            count: Some(5),
+        };
+        keepalive.set(s).unwrap();
+        assert_eq!(getsockopt(s, super::TcpKeepIdle).unwrap(), 60);
+        assert_eq!(getsockopt(s, super::TcpKeepInterval).unwrap(), 10);
+        assert_eq!(getsockopt(s, super::TcpKeepCnt).unwrap(), 5);
+
+        close(s).unwrap();
+    }
 }